@@ -1,5 +1,8 @@
-use std::path::PathBuf;
-use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use audioserve::services::search::fold;
+use audioserve::services::transcode::{Codec, Quality};
+use clap::{Parser, Subcommand, ValueEnum};
 use collection::{common::CollectionTrait, CollectionOptions};
 
 fn default_db() -> String {
@@ -30,6 +33,84 @@ enum Commands {
     Get {
         path: String,
     },
+    Export {
+        /// Folder key to export (as listed by `list`).
+        folder: String,
+        /// Directory to write the transcoded files into.
+        target: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = CliCodec::Opus)]
+        codec: CliCodec,
+        /// Target audio bitrate in kbps.
+        #[arg(short, long, default_value_t = 96)]
+        bitrate: u32,
+    },
+}
+
+// clap can't derive ValueEnum on the shared services::transcode::Codec, so
+// this just maps the CLI-facing names onto it - the actual ffmpeg/container
+// choice per codec lives in one place, the transcode module.
+#[derive(Clone, Copy, ValueEnum)]
+enum CliCodec {
+    Opus,
+    Vorbis,
+    Mp3,
+}
+
+impl CliCodec {
+    fn to_transcode(self) -> Codec {
+        match self {
+            CliCodec::Opus => Codec::Opus,
+            CliCodec::Vorbis => Codec::Vorbis,
+            CliCodec::Mp3 => Codec::Mp3,
+        }
+    }
+}
+
+// Transcode a single file through ffmpeg, then copy the source tags
+// (title/artist/album/track number) onto the produced file so the export is
+// properly labeled.
+fn export_file(src: &Path, dst: &Path, quality: Quality) -> anyhow::Result<()> {
+    use lofty::{Accessor, ItemKey, Probe, TagExt, TaggedFileExt};
+
+    let status = Command::new("ffmpeg")
+        .arg("-y")
+        .args(quality.ffmpeg_export_args(src, dst))
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("ffmpeg failed for {:?}", src);
+    }
+
+    // Copy tags from source to destination.
+    if let Ok(src_tagged) = Probe::open(src).and_then(|p| p.read()) {
+        if let Some(src_tag) = src_tagged.primary_tag().or_else(|| src_tagged.first_tag()) {
+            let mut dst_tagged = Probe::open(dst)?.read()?;
+            let tag_type = dst_tagged.primary_tag_type();
+            if dst_tagged.primary_tag().is_none() {
+                dst_tagged.insert_tag(lofty::Tag::new(tag_type));
+            }
+            let dst_tag = dst_tagged.primary_tag_mut().unwrap();
+            if let Some(v) = src_tag.title() {
+                dst_tag.set_title(v.to_string());
+            }
+            if let Some(v) = src_tag.artist() {
+                dst_tag.set_artist(v.to_string());
+            }
+            if let Some(v) = src_tag.album() {
+                dst_tag.set_album(v.to_string());
+            }
+            if let Some(v) = src_tag.get_string(&ItemKey::AlbumArtist) {
+                dst_tag.insert_text(ItemKey::AlbumArtist, v.to_string());
+            }
+            if let Some(v) = src_tag.track() {
+                dst_tag.set_track(v);
+            }
+            if let Some(v) = src_tag.disk() {
+                dst_tag.set_disk(v);
+            }
+            dst_tag.save_to_path(dst)?;
+        }
+    }
+    Ok(())
 }
 
 macro_rules! exit {
@@ -49,6 +130,7 @@ pub fn main() -> anyhow::Result<()> {
 
     let mut col_opts = CollectionOptions::default();
     col_opts.read_only = true;
+    let base_dir = args.collection.clone();
     let col = collection::cache::CollectionCache::new(args.collection, args.db_path, col_opts)
         .expect("Cannot open collection");
 
@@ -67,7 +149,8 @@ pub fn main() -> anyhow::Result<()> {
         }
         Commands::Search { query } => {
             println!("Searching collection for {}", query);
-            let res = col.search(query, None);
+            // Same accent-insensitive folding as FileSendService's /search.
+            let res = col.search(query, Some(fold));
             for folder in res {
                 println!("{:?}", folder.path);
             }
@@ -77,6 +160,33 @@ pub fn main() -> anyhow::Result<()> {
                 println!("{:?}", f);
             }
         }
+        Commands::Export {
+            folder,
+            target,
+            codec,
+            bitrate,
+        } => {
+            let f = match col.get(folder.clone()) {
+                Some(f) => f,
+                None => {
+                    exit!("Folder {:?} not found in collection", folder);
+                }
+            };
+            let quality = Quality::new(codec.to_transcode(), bitrate);
+            std::fs::create_dir_all(&target)?;
+            for file in f.files {
+                let src = base_dir.join(&file.path);
+                let stem = Path::new(&file.name)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or(&file.name);
+                let dst = target.join(format!("{}.{}", stem, quality.codec.ffmpeg_format()));
+                println!("Exporting {:?} -> {:?}", src, dst);
+                if let Err(e) = export_file(&src, &dst, quality) {
+                    eprintln!("Failed to export {:?}: {}", src, e);
+                }
+            }
+        }
     }
 
     Ok(())
@@ -4,8 +4,9 @@ use hyper::{Body, Response};
 use image::io::Reader as ImageReader;
 use image::ImageOutputFormat;
 use std::{
+    fs::read_dir,
     io::{Cursor, Read},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use crate::{config::get_config, util::ResponseBuilderExt};
@@ -44,9 +45,132 @@ pub fn icon_response(path: impl AsRef<Path>) -> Result<Response<Body>> {
         .map_err(anyhow::Error::from)
 }
 
+// Find cover art for a folder: a cover image file if present, otherwise the
+// first embedded picture from one of the folder's audio files, scaled and
+// cached through the same cache_icon/cached_icon mechanism as icon_response,
+// keyed by folder. Returns the raw PNG bytes so callers can wrap them in
+// whatever response type their handler uses.
+pub fn folder_icon_bytes(folder: impl AsRef<Path>) -> Result<Option<Vec<u8>>> {
+    let folder = folder.as_ref();
+    if let Some(cover) = find_cover_file(folder) {
+        return scale_cover(cover).map(Some);
+    }
+
+    let cache_enabled = !get_config().icons.cache_disabled;
+    let data = match if cache_enabled {
+        cached_icon(folder)
+    } else {
+        None
+    } {
+        Some(mut f) => {
+            let mut data = Vec::with_capacity(1024);
+            f.read_to_end(&mut data)?;
+            data
+        }
+        None => {
+            let embedded = match find_embedded_cover(folder)? {
+                Some(bytes) => bytes,
+                None => return Ok(None),
+            };
+            let data = scale_cover_bytes(&embedded)?;
+            if cache_enabled {
+                cache_icon(folder, &data)
+                    .unwrap_or_else(|e| error!("error adding icon to cache: {}", e));
+            }
+            data
+        }
+    };
+
+    Ok(Some(data))
+}
+
+// Same as folder_icon_bytes, wrapped as an HTTP response for callers on this
+// module's hyper generation.
+pub fn folder_icon_response(folder: impl AsRef<Path>) -> Result<Option<Response<Body>>> {
+    let data = match folder_icon_bytes(folder)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+
+    Response::builder()
+        .status(200)
+        .typed_header(ContentLength(data.len() as u64))
+        .typed_header(ContentType::png())
+        .body(data.into())
+        .map_err(anyhow::Error::from)
+        .map(Some)
+}
+
+fn find_cover_file(folder: &Path) -> Option<PathBuf> {
+    let entries = read_dir(folder).ok()?;
+    for e in entries.flatten() {
+        let p = e.path();
+        let ext = p
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        if matches!(ext.as_deref(), Some("jpg") | Some("jpeg") | Some("png")) {
+            return Some(p);
+        }
+    }
+    None
+}
+
+// Return the bytes of the first embedded picture (ID3 APIC, FLAC/Vorbis
+// PICTURE, MP4 covr) found in a representative audio file of the folder.
+fn find_embedded_cover(folder: &Path) -> Result<Option<Vec<u8>>> {
+    use lofty::{Probe, TaggedFileExt};
+
+    let mut audio: Vec<PathBuf> = read_dir(folder)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_audio_ext(p))
+        .collect();
+    audio.sort();
+
+    for p in audio {
+        let tagged = match Probe::open(&p).and_then(|t| t.read()) {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+        let pic = tagged
+            .primary_tag()
+            .or_else(|| tagged.first_tag())
+            .and_then(|t| t.pictures().first());
+        if let Some(pic) = pic {
+            return Ok(Some(pic.data().to_vec()));
+        }
+    }
+    Ok(None)
+}
+
+fn is_audio_ext(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    matches!(
+        ext.as_deref(),
+        Some("mp3") | Some("ogg") | Some("opus") | Some("flac") | Some("m4a") | Some("m4b")
+    )
+}
+
 pub fn scale_cover(path: impl AsRef<Path>) -> Result<Vec<u8>> {
-    use image::imageops::FilterType;
     let img = ImageReader::open(path)?.decode()?;
+    scale_image(img)
+}
+
+// Scale cover art already in memory (e.g. extracted from an audio file).
+// ImageReader decodes arbitrary formats, so the source can be jpeg/png/etc.
+pub fn scale_cover_bytes(bytes: &[u8]) -> Result<Vec<u8>> {
+    let img = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()?;
+    scale_image(img)
+}
+
+fn scale_image(img: image::DynamicImage) -> Result<Vec<u8>> {
+    use image::imageops::FilterType;
     let sz = get_config().icons.size;
     let scaled = img.resize(
         sz,
@@ -1,27 +1,36 @@
 use self::auth::Authenticator;
+use self::ranges::ByteRange;
 use self::search::Search;
 use self::subs::{
-    collections_list, get_folder, search, send_file, send_file_simple, short_response_boxed,
+    collections_list, get_folder, send_file, send_file_simple, short_response_boxed,
     transcodings_list, ResponseFuture, NOT_FOUND_MESSAGE,
 };
 use self::transcode::QualityLevel;
 use config::get_config;
 use futures::{future, Future};
-use hyper::header::{AccessControlAllowCredentials, AccessControlAllowOrigin, Origin, Range};
+use hyper::header::{
+    AcceptEncoding, AccessControlAllowCredentials, AccessControlAllowOrigin, ByteRangeSpec,
+    ContentLength, ContentRange, ContentRangeSpec, ContentType, Encoding, Origin, Range,
+};
+use mime_guess::guess_mime_type;
 use hyper::server::{Request, Response, Service};
 use hyper::{Method, StatusCode};
 use percent_encoding::percent_decode;
 use regex::Regex;
 use simple_thread_pool::Pool;
 use std::collections::HashMap;
-use std::fs::{read_link, DirEntry};
+use std::fs::{self, read_link, DirEntry, File};
 use std::io;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicUsize;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use url::form_urlencoded;
 
 pub mod auth;
+pub mod icon;
+pub mod ranges;
 pub mod search;
 mod subs;
 pub mod transcode;
@@ -56,6 +65,267 @@ fn get_subpath(path: &str, prefix: &str) -> PathBuf {
     Path::new(&path).strip_prefix(prefix).unwrap().to_path_buf()
 }
 
+// Serve a static client asset, negotiating Accept-Encoding: if the client
+// accepts br or gzip and a precompressed sibling (e.g. bundle.js.br) exists,
+// serve that file's bytes with the matching Content-Encoding and a
+// Vary: Accept-Encoding header, keeping the original MIME type and cache-age.
+// Brotli is preferred over gzip when both are offered and present.
+fn send_static(req: &Request, filename: &'static str, pool: Pool) -> ResponseFuture {
+    let client_dir = &get_config().client_dir;
+    let accepts = |enc: &Encoding| {
+        req.headers()
+            .get::<AcceptEncoding>()
+            .map(|a| a.iter().any(|q| q.quality.0 > 0 && q.item == *enc))
+            .unwrap_or(false)
+    };
+    let sibling_exists = |ext: &str| Path::new(client_dir).join(format!("{}.{}", filename, ext)).is_file();
+
+    let (served, encoding) = if accepts(&Encoding::Brotli) && sibling_exists("br") {
+        (format!("{}.br", filename), Some("br"))
+    } else if accepts(&Encoding::Gzip) && sibling_exists("gz") {
+        (format!("{}.gz", filename), Some("gzip"))
+    } else {
+        (filename.to_string(), None)
+    };
+
+    let resp = send_file_simple(
+        client_dir,
+        served.into(),
+        Some(APP_STATIC_FILES_CACHE_AGE),
+        pool,
+    );
+
+    match encoding {
+        None => resp,
+        Some(enc) => {
+            // Keep the uncompressed file's MIME, not the .br/.gz guess.
+            let mime = guess_mime_type(filename);
+            Box::new(resp.map(move |mut r| {
+                {
+                    let headers = r.headers_mut();
+                    headers.set_raw("Content-Type", mime.as_ref().to_string());
+                    headers.set_raw("Content-Encoding", enc);
+                    headers.set_raw("Vary", "Accept-Encoding");
+                }
+                r
+            }))
+        }
+    }
+}
+
+// Build a plain (non-future) error response - used inside pool-spawned
+// closures, which hand back a Response directly rather than a ResponseFuture.
+fn error_response(status: StatusCode, msg: &'static str) -> Response {
+    Response::new()
+        .with_status(status)
+        .with_header(ContentType::plaintext())
+        .with_header(ContentLength(msg.len() as u64))
+        .with_body(msg)
+}
+
+// Serialize a response body to JSON, matching the /collections and
+// /transcodings endpoints.
+fn json_body_response<T: ::serde::Serialize>(value: &T) -> Response {
+    match ::serde_json::to_string(value) {
+        Ok(body) => Response::new().with_header(ContentType::json()).with_body(body),
+        Err(e) => {
+            error!("Failed to serialize JSON response: {}", e);
+            error_response(StatusCode::InternalServerError, "Serialization error")
+        }
+    }
+}
+
+// Run search_folders on the thread pool, like every other disk-touching
+// handler in this file, and serialize the result as JSON.
+fn search_response(searcher: Search, query: String, base_dir: String, pool: Pool) -> ResponseFuture {
+    Box::new(
+        pool.spawn(move || searcher.search_folders(&query, &base_dir))
+            .then(|result| {
+                future::ok(match result {
+                    Ok(found) => json_body_response(&found),
+                    Err(e) => {
+                        error!("Search thread pool error: {}", e);
+                        error_response(StatusCode::InternalServerError, "Internal error")
+                    }
+                })
+            }),
+    )
+}
+
+// Serve one or more byte ranges of the original (non-transcoded) audio file:
+// a single range gets a plain 206 body with a Content-Range header, more
+// than one gets a 206 multipart/byteranges body built from the
+// services::ranges helpers. Runs on the thread pool like every other
+// disk-touching handler in this file - a large multi-range read shouldn't
+// stall the reactor thread.
+fn send_audio_ranges(base_dir: &str, subpath: PathBuf, specs: Vec<ByteRangeSpec>, pool: Pool) -> ResponseFuture {
+    let base_dir = base_dir.to_string();
+    Box::new(
+        pool.spawn(move || build_range_response(&base_dir, &subpath, &specs))
+            .then(|result| {
+                future::ok(match result {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        error!("Ranges thread pool error: {}", e);
+                        error_response(StatusCode::InternalServerError, "Internal error")
+                    }
+                })
+            }),
+    )
+}
+
+fn build_range_response(base_dir: &str, subpath: &Path, specs: &[ByteRangeSpec]) -> Response {
+    let full_path = Path::new(base_dir).join(subpath);
+    let file_len = match fs::metadata(&full_path) {
+        Ok(m) => m.len(),
+        Err(_) => return error_response(StatusCode::NotFound, NOT_FOUND_MESSAGE),
+    };
+
+    let parts: Vec<ByteRange> = specs
+        .iter()
+        .filter_map(|s| ranges::normalize(s, file_len))
+        .collect();
+    if parts.is_empty() {
+        return error_response(
+            StatusCode::RangeNotSatisfiable,
+            "Requested range is not satisfiable",
+        );
+    }
+
+    let mut file = match File::open(&full_path) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Cannot open {:?}: {}", full_path, e);
+            return error_response(StatusCode::InternalServerError, "Cannot open file");
+        }
+    };
+    let mime = guess_mime_type(&full_path);
+    let content_type = mime.as_ref().to_string();
+
+    if parts.len() == 1 {
+        let r = &parts[0];
+        return match read_exact_range(&mut file, r) {
+            Ok(body) => Response::new()
+                .with_status(StatusCode::PartialContent)
+                .with_header(ContentType(mime))
+                .with_header(ContentLength(body.len() as u64))
+                .with_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((r.start, r.end)),
+                    complete_length: Some(file_len),
+                }))
+                .with_body(body),
+            Err(e) => {
+                error!("Cannot read {:?}: {}", full_path, e);
+                error_response(StatusCode::InternalServerError, "Cannot read file")
+            }
+        };
+    }
+
+    let boundary = ranges::boundary(file_len, parts.len());
+    let mut body = Vec::with_capacity(
+        ranges::multipart_content_length(&parts, &boundary, &content_type, file_len) as usize,
+    );
+    for r in &parts {
+        body.extend_from_slice(
+            ranges::part_header(&boundary, &content_type, r, file_len).as_bytes(),
+        );
+        match read_exact_range(&mut file, r) {
+            Ok(bytes) => body.extend_from_slice(&bytes),
+            Err(e) => {
+                error!("Cannot read {:?}: {}", full_path, e);
+                return error_response(StatusCode::InternalServerError, "Cannot read file");
+            }
+        }
+    }
+    body.extend_from_slice(ranges::closing(&boundary).as_bytes());
+
+    let mut resp = Response::new()
+        .with_status(StatusCode::PartialContent)
+        .with_header(ContentLength(body.len() as u64))
+        .with_body(body);
+    resp.headers_mut().set_raw(
+        "Content-Type",
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+    resp
+}
+
+fn read_exact_range(file: &mut File, r: &ByteRange) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(r.start))?;
+    let mut buf = vec![0u8; r.len() as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// Transcode the original file to the quality level picked by ?trans= and
+// stream the whole encoded body back. Concurrency is capped at
+// max_transcodings, same limit /transcodings advertises. The encoded length
+// isn't known upfront (see the comment above this function's call site), so
+// this never serves a byte range - only the original file can do that. ffmpeg
+// runs on the thread pool, same as every other blocking op in this file -
+// it can easily take as long as a whole chapter and must not block the
+// reactor thread for that long.
+fn send_transcoded_audio(
+    base_dir: &str,
+    subpath: PathBuf,
+    seek: Option<f32>,
+    transcoding: TranscodingDetails,
+    level: QualityLevel,
+    pool: Pool,
+) -> ResponseFuture {
+    if transcoding.transcodings.fetch_add(1, Ordering::SeqCst) >= transcoding.max_transcodings {
+        transcoding.transcodings.fetch_sub(1, Ordering::SeqCst);
+        return short_response_boxed(StatusCode::ServiceUnavailable, "Too many transcodings");
+    }
+
+    let full_path = Path::new(base_dir).join(&subpath);
+    let quality = get_config().transcoding.get(level);
+    let mut args = quality.ffmpeg_args(&full_path);
+    if let Some(secs) = seek {
+        args.splice(0..0, vec!["-ss".to_string(), format!("{}", secs)]);
+    }
+
+    let counter = transcoding.transcodings.clone();
+    Box::new(
+        pool.spawn(move || run_ffmpeg(&full_path, &args, quality.mime()))
+            .then(move |result| {
+                counter.fetch_sub(1, Ordering::SeqCst);
+                future::ok(match result {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        error!("Transcoding thread pool error: {}", e);
+                        error_response(StatusCode::InternalServerError, "Transcoding failed")
+                    }
+                })
+            }),
+    )
+}
+
+fn run_ffmpeg(full_path: &Path, args: &[String], mime: &str) -> Response {
+    match Command::new("ffmpeg").args(args).output() {
+        Ok(out) => {
+            if out.status.success() {
+                Response::new()
+                    .with_header(ContentType(mime.parse().unwrap()))
+                    .with_header(ContentLength(out.stdout.len() as u64))
+                    .with_body(out.stdout)
+            } else {
+                error!(
+                    "ffmpeg exited with {} transcoding {:?}: {}",
+                    out.status,
+                    full_path,
+                    String::from_utf8_lossy(&out.stderr)
+                );
+                error_response(StatusCode::InternalServerError, "Transcoding failed")
+            }
+        }
+        Err(e) => {
+            error!("Cannot spawn ffmpeg for {:?}: {}", full_path, e);
+            error_response(StatusCode::InternalServerError, "Transcoding failed")
+        }
+    }
+}
+
 fn add_cors_headers(resp: Response, origin: Option<String>, enabled: bool) -> Response {
     if !enabled {
         return resp;
@@ -81,20 +351,10 @@ impl Service for FileSendService {
         };
         //static files
         if req.path() == "/" {
-            return send_file_simple(
-                &get_config().client_dir,
-                "index.html".into(),
-                Some(APP_STATIC_FILES_CACHE_AGE),
-                self.pool.clone(),
-            );
+            return send_static(&req, "index.html", self.pool.clone());
         };
         if req.path() == "/bundle.js" {
-            return send_file_simple(
-                &get_config().client_dir,
-                "bundle.js".into(),
-                Some(APP_STATIC_FILES_CACHE_AGE),
-                self.pool.clone(),
-            );
+            return send_static(&req, "bundle.js", self.pool.clone());
         }
         // from here everything must be authenticated
         let pool = self.pool.clone();
@@ -168,21 +428,17 @@ impl FileSendService {
                         debug!("Received request with following headers {}", req.headers());
 
                         let range = req.headers().get::<Range>();
-                        let bytes_range = match range {
+                        let bytes_ranges = match range {
                             Some(&Range::Bytes(ref bytes_ranges)) => {
                                 if bytes_ranges.is_empty() {
                                     return short_response_boxed(
                                         StatusCode::BadRequest,
                                         "One range is required",
                                     );
-                                } else if bytes_ranges.len() > 1 {
-                                    return short_response_boxed(
-                                        StatusCode::NotImplemented,
-                                        "Do not support muptiple ranges",
-                                    );
-                                } else {
-                                    Some(bytes_ranges[0].clone())
                                 }
+                                // A single range yields 206 with a plain body;
+                                // N>1 ranges yield a multipart/byteranges body.
+                                bytes_ranges.clone()
                             }
                             Some(_) => {
                                 return short_response_boxed(
@@ -190,7 +446,7 @@ impl FileSendService {
                                     "Other then bytes ranges are not supported",
                                 )
                             }
-                            None => None,
+                            None => vec![],
                         };
                         let seek: Option<f32> = params
                             .as_mut()
@@ -199,30 +455,78 @@ impl FileSendService {
                         let transcoding_quality: Option<QualityLevel> = params
                             .and_then(|mut p| p.remove("trans"))
                             .and_then(|t| QualityLevel::from_letter(&t));
+                        let subpath = get_subpath(&path, "/audio/");
 
-                        send_file(
-                            base_dir,
-                            get_subpath(&path, "/audio/"),
-                            bytes_range,
-                            seek,
-                            pool,
-                            transcoding,
-                            transcoding_quality,
-                        )
+                        // A transcoded stream's encoded length isn't known
+                        // upfront, so it can't be byte-range-seeked - only
+                        // the original file can serve a real 206/multipart
+                        // response. A transcoded request goes to
+                        // send_transcoded_audio, which ignores any Range
+                        // header; an unranged original goes to send_file.
+                        if transcoding_quality.is_none() && !bytes_ranges.is_empty() {
+                            return send_audio_ranges(base_dir, subpath, bytes_ranges, pool.clone());
+                        }
+
+                        match transcoding_quality {
+                            Some(level) => send_transcoded_audio(
+                                base_dir,
+                                subpath,
+                                seek,
+                                transcoding,
+                                level,
+                                pool,
+                            ),
+                            None => send_file(
+                                base_dir,
+                                subpath,
+                                bytes_ranges.into_iter().next(),
+                                seek,
+                                pool,
+                                transcoding,
+                                transcoding_quality,
+                            ),
+                        }
                     } else if path.starts_with("/folder/") {
                         get_folder(base_dir, get_subpath(&path, "/folder/"), pool)
                     } else if path == "/search" {
                         if let Some(search_string) = params.and_then(|mut p| p.remove("q")) {
-                            return search(base_dir, searcher, search_string.into_owned(), pool);
+                            return search_response(
+                                searcher,
+                                search_string.into_owned(),
+                                base_dir.clone(),
+                                pool,
+                            );
                         }
                         short_response_boxed(StatusCode::NotFound, NOT_FOUND_MESSAGE)
                     } else if path.starts_with("/cover/") {
-                        send_file_simple(
+                        let rel_path = get_subpath(&path, "/cover");
+                        let folder = Path::new(base_dir).join(&rel_path);
+                        let primary = send_file_simple(
                             base_dir,
-                            get_subpath(&path, "/cover"),
+                            rel_path,
                             Some(FOLDER_INFO_FILES_CACHE_AGE),
                             pool,
-                        )
+                        );
+                        // No cover file on disk at that path - fall back to
+                        // art embedded in one of the folder's audio files.
+                        Box::new(primary.and_then(move |resp| {
+                            if resp.status() != StatusCode::NotFound {
+                                return future::ok(resp);
+                            }
+                            match icon::folder_icon_bytes(&folder) {
+                                Ok(Some(data)) => future::ok(
+                                    Response::new()
+                                        .with_header(ContentLength(data.len() as u64))
+                                        .with_header(ContentType::png())
+                                        .with_body(data),
+                                ),
+                                Ok(None) => future::ok(resp),
+                                Err(e) => {
+                                    error!("Error reading embedded folder icon: {}", e);
+                                    future::ok(resp)
+                                }
+                            }
+                        }))
                     } else if path.starts_with("/desc/") {
                         send_file_simple(
                             base_dir,
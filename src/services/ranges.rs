@@ -0,0 +1,142 @@
+use hyper::header::ByteRangeSpec;
+
+// A normalized, inclusive byte range clamped against the file length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64, // inclusive
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Normalize a requested range against the file length, following RFC 7233:
+// `bytes=start-end`, `bytes=start-` (to end of file) and `bytes=-suffix`
+// (last suffix bytes). Returns None for a genuinely unsatisfiable range.
+pub fn normalize(spec: &ByteRangeSpec, file_len: u64) -> Option<ByteRange> {
+    if file_len == 0 {
+        return None;
+    }
+    match *spec {
+        ByteRangeSpec::FromTo(start, end) => {
+            if start >= file_len {
+                return None;
+            }
+            let end = end.min(file_len - 1);
+            if end < start {
+                return None;
+            }
+            Some(ByteRange { start, end })
+        }
+        ByteRangeSpec::AllFrom(start) => {
+            if start >= file_len {
+                return None;
+            }
+            Some(ByteRange {
+                start,
+                end: file_len - 1,
+            })
+        }
+        ByteRangeSpec::Last(suffix) => {
+            if suffix == 0 {
+                return None;
+            }
+            let suffix = suffix.min(file_len);
+            Some(ByteRange {
+                start: file_len - suffix,
+                end: file_len - 1,
+            })
+        }
+    }
+}
+
+// A generated multipart boundary. Kept short but collision-unlikely by mixing
+// in the file length and range count.
+pub fn boundary(file_len: u64, parts: usize) -> String {
+    format!("audioserve_{:x}_{:x}", file_len, parts)
+}
+
+// The per-part header block emitted before each range's bytes in a
+// multipart/byteranges body.
+pub fn part_header(boundary: &str, content_type: &str, range: &ByteRange, file_len: u64) -> String {
+    format!(
+        "\r\n--{boundary}\r\nContent-Type: {ct}\r\nContent-Range: bytes {start}-{end}/{total}\r\n\r\n",
+        boundary = boundary,
+        ct = content_type,
+        start = range.start,
+        end = range.end,
+        total = file_len,
+    )
+}
+
+// The closing delimiter after the last part.
+pub fn closing(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+// Total Content-Length of a multipart/byteranges body: every part header and
+// its bytes, plus the closing boundary.
+pub fn multipart_content_length(
+    ranges: &[ByteRange],
+    boundary: &str,
+    content_type: &str,
+    file_len: u64,
+) -> u64 {
+    let mut total = 0u64;
+    for r in ranges {
+        total += part_header(boundary, content_type, r, file_len).len() as u64;
+        total += r.len();
+    }
+    total + closing(boundary).len() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(&ByteRangeSpec::FromTo(0, 499), 1000),
+            Some(ByteRange { start: 0, end: 499 })
+        );
+        // end clamped to file length
+        assert_eq!(
+            normalize(&ByteRangeSpec::FromTo(900, 5000), 1000),
+            Some(ByteRange { start: 900, end: 999 })
+        );
+        assert_eq!(
+            normalize(&ByteRangeSpec::AllFrom(500), 1000),
+            Some(ByteRange { start: 500, end: 999 })
+        );
+        assert_eq!(
+            normalize(&ByteRangeSpec::Last(200), 1000),
+            Some(ByteRange { start: 800, end: 999 })
+        );
+        // start past the end is unsatisfiable
+        assert_eq!(normalize(&ByteRangeSpec::FromTo(1000, 1100), 1000), None);
+    }
+
+    #[test]
+    fn test_multipart_length() {
+        let ranges = vec![
+            ByteRange { start: 0, end: 9 },
+            ByteRange { start: 20, end: 29 },
+        ];
+        let b = boundary(1000, ranges.len());
+        let len = multipart_content_length(&ranges, &b, "audio/mpeg", 1000);
+        let expected: u64 = ranges
+            .iter()
+            .map(|r| part_header(&b, "audio/mpeg", r, 1000).len() as u64 + r.len())
+            .sum::<u64>()
+            + closing(&b).len() as u64;
+        assert_eq!(len, expected);
+    }
+}
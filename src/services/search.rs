@@ -0,0 +1,195 @@
+use config::get_config;
+use services::types::{is_audio, AudioFile, AudioFolderShort, AudioMeta, SearchResult, TypedFile};
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Letters NFD cannot decompose into ASCII base + combining mark, so we fold
+// them explicitly.  Kept small on purpose - only the cases that show up in
+// real collection names. Matched on both cases, as uppercase forms (Æ, Ø,
+// Đ, Ł) don't NFD-decompose either and would otherwise fall through.
+fn fold_special(c: char) -> Option<&'static str> {
+    match c {
+        'ß' => Some("ss"),
+        'æ' | 'Æ' => Some("ae"),
+        'ø' | 'Ø' => Some("o"),
+        'đ' | 'Đ' => Some("d"),
+        'ł' | 'Ł' => Some("l"),
+        _ => None,
+    }
+}
+
+// ASCII-fold a string: NFD decompose, drop every combining mark (any
+// General_Category Mark - nonspacing accents, but also spacing and enclosing
+// marks), lowercase and collapse runs of whitespace to a single space.
+pub fn fold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last_ws = false;
+    for c in s.nfd() {
+        if is_combining_mark(c) {
+            continue;
+        }
+        if let Some(rep) = fold_special(c) {
+            out.push_str(rep);
+            last_ws = false;
+            continue;
+        }
+        if c.is_whitespace() {
+            if !last_ws && !out.is_empty() {
+                out.push(' ');
+            }
+            last_ws = true;
+            continue;
+        }
+        last_ws = false;
+        for lc in c.to_lowercase() {
+            out.push(lc);
+        }
+    }
+    if out.ends_with(' ') {
+        out.pop();
+    }
+    out
+}
+
+// Tags read from one audio file, cached by mtime: as long as a file's mtime
+// hasn't changed since its tags were last read, reuse the cached copy
+// instead of re-parsing it on every /search request.
+struct CachedTags {
+    mtime: SystemTime,
+    meta: AudioMeta,
+}
+
+#[derive(Clone)]
+pub struct Search {
+    fold: bool,
+    tag_cache: Arc<Mutex<HashMap<PathBuf, CachedTags>>>,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Search {
+            fold: get_config().search_fold,
+            tag_cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Normalized form applied to both the walked name and the query on every
+    // match, so both sides go through the same transformation. No index of
+    // normalized names is kept; names are normalized as they are walked.
+    pub fn normalize(&self, s: &str) -> String {
+        if self.fold {
+            fold(s)
+        } else {
+            s.to_lowercase()
+        }
+    }
+
+    pub fn matches(&self, name: &str, query: &str) -> bool {
+        self.normalize(name).contains(&self.normalize(query))
+    }
+
+    // Recursively walk base_dir, keeping every folder and audio file whose
+    // name matches the (folded) query - so "beyonce" finds both a Beyoncé
+    // folder and a stray Beyoncé.mp3 file. Results keep the original
+    // path/name, relative to base_dir.
+    pub fn search_folders<P: AsRef<Path>>(&self, query: &str, base_dir: P) -> SearchResult {
+        let mut result = SearchResult {
+            files: vec![],
+            subfolders: vec![],
+        };
+        self.walk(base_dir.as_ref(), Path::new(""), query, &mut result);
+        result
+    }
+
+    fn walk(&self, base_dir: &Path, rel_dir: &Path, query: &str, result: &mut SearchResult) {
+        let entries = match read_dir(base_dir.join(rel_dir)) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let rel_path = rel_dir.join(&name);
+            if path.is_dir() {
+                if self.matches(&name, query) {
+                    result.subfolders.push(AudioFolderShort {
+                        name: name.clone(),
+                        path: rel_path.clone(),
+                    });
+                }
+                self.walk(base_dir, &rel_path, query, result);
+            } else if is_audio(&path) {
+                // Tags aren't indexed anywhere in this tree, so read them via
+                // the mtime-checked cache and match on title/artist/album too
+                // - not just the file name - the same fields AudioMeta
+                // exposes in /folder/.
+                let meta = self.cached_tags(&path);
+                let tag_matches = [&meta.title, &meta.artist, &meta.album]
+                    .iter()
+                    .filter_map(|t| t.as_ref())
+                    .any(|t| self.matches(t, query));
+                if self.matches(&name, query) || tag_matches {
+                    result.files.push(AudioFile {
+                        name,
+                        mime: TypedFile::new(rel_path.clone()).mime,
+                        path: rel_path,
+                        meta: Some(meta),
+                    });
+                }
+            }
+        }
+    }
+
+    // Read embedded tags for `path`, reusing the cached copy as long as the
+    // file's mtime hasn't changed since it was last read.
+    fn cached_tags(&self, path: &Path) -> AudioMeta {
+        let mtime = path.metadata().and_then(|m| m.modified()).ok();
+        if let Some(mtime) = mtime {
+            if let Some(cached) = self.tag_cache.lock().unwrap().get(path) {
+                if cached.mtime == mtime {
+                    return cached.meta.clone();
+                }
+            }
+        }
+
+        let mut meta = AudioMeta::default();
+        meta.read_tags(path);
+        if let Some(mtime) = mtime {
+            let cached = CachedTags {
+                mtime,
+                meta: meta.clone(),
+            };
+            self.tag_cache.lock().unwrap().insert(path.to_path_buf(), cached);
+        }
+        meta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_accents() {
+        assert_eq!(fold("Beyoncé"), "beyonce");
+        assert_eq!(fold("Björk"), "bjork");
+        assert_eq!(fold("Motörhead"), "motorhead");
+    }
+
+    #[test]
+    fn test_fold_special_letters() {
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("Æther"), "aether");
+        assert_eq!(fold("Håkon Øyen"), "hakon oyen");
+    }
+
+    #[test]
+    fn test_fold_whitespace() {
+        assert_eq!(fold("  The   Beatles \t"), "the beatles");
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+// The three quality levels exposed to the client through the `trans` query
+// param. Each level resolves to a Quality (codec + bitrate) from the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QualityLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl QualityLevel {
+    pub fn from_letter(s: &str) -> Option<Self> {
+        match s {
+            "l" => Some(QualityLevel::Low),
+            "m" => Some(QualityLevel::Medium),
+            "h" => Some(QualityLevel::High),
+            _ => None,
+        }
+    }
+
+    pub fn to_letter(self) -> &'static str {
+        match self {
+            QualityLevel::Low => "l",
+            QualityLevel::Medium => "m",
+            QualityLevel::High => "h",
+        }
+    }
+}
+
+// Output codec an operator can pick per quality level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    Opus,
+    Vorbis,
+    Mp3,
+}
+
+impl Codec {
+    // ffmpeg encoder and container format for this codec.
+    pub fn ffmpeg_codec(self) -> &'static str {
+        match self {
+            Codec::Opus => "libopus",
+            Codec::Vorbis => "libvorbis",
+            Codec::Mp3 => "libmp3lame",
+        }
+    }
+
+    pub fn ffmpeg_format(self) -> &'static str {
+        match self {
+            Codec::Opus | Codec::Vorbis => "ogg",
+            Codec::Mp3 => "mp3",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Codec::Opus | Codec::Vorbis => "audio/ogg",
+            Codec::Mp3 => "audio/mpeg",
+        }
+    }
+}
+
+// A resolved quality: which codec to encode to and at what bitrate (kbps).
+// There are no named bitrate presets; any codec/bitrate pair can be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quality {
+    pub codec: Codec,
+    pub bitrate: u32,
+}
+
+impl Quality {
+    pub fn new(codec: Codec, bitrate: u32) -> Self {
+        Quality { codec, bitrate }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        self.codec.mime()
+    }
+
+    // ffmpeg arguments to transcode `input` to this quality on stdout.
+    pub fn ffmpeg_args<P: AsRef<Path>>(&self, input: P) -> Vec<String> {
+        vec![
+            "-i".into(),
+            input.as_ref().to_string_lossy().into_owned(),
+            "-acodec".into(),
+            self.codec.ffmpeg_codec().into(),
+            "-b:a".into(),
+            format!("{}k", self.bitrate),
+            "-f".into(),
+            self.codec.ffmpeg_format().into(),
+            "-".into(),
+        ]
+    }
+
+    // Same as ffmpeg_args, but writing directly to `output` instead of
+    // stdout - used by the offline CLI export, where the destination is a
+    // real file rather than a streamed response body.
+    pub fn ffmpeg_export_args<P: AsRef<Path>>(&self, input: P, output: P) -> Vec<String> {
+        vec![
+            "-i".into(),
+            input.as_ref().to_string_lossy().into_owned(),
+            "-acodec".into(),
+            self.codec.ffmpeg_codec().into(),
+            "-b:a".into(),
+            format!("{}k", self.bitrate),
+            output.as_ref().to_string_lossy().into_owned(),
+        ]
+    }
+}
+
+// Per-level codec/bitrate mapping, chosen by the operator. Defaults keep the
+// previous behavior (Opus, great at 96k for speech/audiobooks) while allowing
+// MP3/Vorbis tiers.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transcoding {
+    pub low: Quality,
+    pub medium: Quality,
+    pub high: Quality,
+}
+
+impl Default for Transcoding {
+    fn default() -> Self {
+        Transcoding {
+            low: Quality::new(Codec::Opus, 32),
+            medium: Quality::new(Codec::Opus, 48),
+            high: Quality::new(Codec::Opus, 96),
+        }
+    }
+}
+
+impl Transcoding {
+    pub fn get(&self, level: QualityLevel) -> Quality {
+        match level {
+            QualityLevel::Low => self.low,
+            QualityLevel::Medium => self.medium,
+            QualityLevel::High => self.high,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_letter_roundtrip() {
+        for l in &[QualityLevel::Low, QualityLevel::Medium, QualityLevel::High] {
+            assert_eq!(QualityLevel::from_letter(l.to_letter()), Some(*l));
+        }
+        assert_eq!(QualityLevel::from_letter("x"), None);
+    }
+
+    #[test]
+    fn test_codec_mime() {
+        assert_eq!(Quality::new(Codec::Opus, 96).mime(), "audio/ogg");
+        assert_eq!(Quality::new(Codec::Mp3, 320).mime(), "audio/mpeg");
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        let args = Quality::new(Codec::Mp3, 320).ffmpeg_args("in.m4a");
+        assert!(args.contains(&"libmp3lame".to_string()));
+        assert!(args.contains(&"320k".to_string()));
+        assert_eq!(args.last().unwrap(), "-");
+    }
+
+    #[test]
+    fn test_ffmpeg_export_args() {
+        let args = Quality::new(Codec::Opus, 96).ffmpeg_export_args("in.flac", "out.ogg");
+        assert!(args.contains(&"libopus".to_string()));
+        assert!(args.contains(&"96k".to_string()));
+        assert_eq!(args.last().unwrap(), "out.ogg");
+    }
+}
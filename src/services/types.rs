@@ -2,6 +2,8 @@ use config::get_config;
 use mime::Mime;
 use mime_guess::guess_mime_type;
 use services::transcode::{Quality, QualityLevel};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize)]
@@ -21,7 +23,7 @@ impl TypedFile {
     }
 }
 
-#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Serialize, PartialEq)]
 pub struct AudioFile {
     pub name: String,
     pub path: PathBuf,
@@ -29,10 +31,83 @@ pub struct AudioFile {
     pub mime: String,
 }
 
-#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+// AudioMeta now carries a free-form tags map, which is not Ord, so files are
+// ordered by name (then path) - the order used when listing a folder.
+impl Eq for AudioFile {}
+impl PartialOrd for AudioFile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for AudioFile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then_with(|| self.path.cmp(&other.path))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Default)]
 pub struct AudioMeta {
     pub duration: u32, // duration in seconds, if available
     pub bitrate: u32,  // bitrate in kB/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_no: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disc_no: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+}
+
+impl AudioMeta {
+    // Read embedded tags (ID3v2 for mp3, Vorbis comments for ogg/opus/flac,
+    // MP4 atoms for m4a/m4b) into the optional fields and the tags catch-all.
+    // Called by Search when it matches a file's tags; the collection does
+    // not call it when indexing. duration/bitrate come from the media info
+    // and are left untouched.
+    pub fn read_tags<P: AsRef<Path>>(&mut self, path: P) {
+        use lofty::{Accessor, ItemKey, ItemValue, Probe, TaggedFileExt};
+
+        let tagged = match Probe::open(path).and_then(|p| p.read()) {
+            Ok(t) => t,
+            Err(e) => {
+                debug!("Cannot read tags: {}", e);
+                return;
+            }
+        };
+        let tag = match tagged.primary_tag().or_else(|| tagged.first_tag()) {
+            Some(t) => t,
+            None => return,
+        };
+
+        self.title = tag.title().map(|s| s.to_string());
+        self.artist = tag.artist().map(|s| s.to_string());
+        self.album = tag.album().map(|s| s.to_string());
+        self.album_artist = tag
+            .get_string(&ItemKey::AlbumArtist)
+            .map(|s| s.to_string());
+        self.track_no = tag.track();
+        self.disc_no = tag.disk();
+        self.year = tag.year().map(|y| y as i32);
+
+        for item in tag.items() {
+            if let ItemValue::Text(ref v) = item.value() {
+                if let ItemKey::Unknown(ref k) = item.key() {
+                    self.tags.insert(k.clone(), v.clone());
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]